@@ -1,10 +1,72 @@
+use std::f32::consts::TAU;
+use std::ops::{Add, Neg, Sub};
+
 use ggez::{graphics::Color, mint::Point2};
 
+/// Angle in radians, always normalized into `[0, 2π)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Angle(f32);
+
+impl Angle {
+    /// New angle from radians, normalized into `[0, 2π)`
+    pub fn radians(radians: f32) -> Self {
+        let wrapped = radians % TAU;
+        let wrapped = if wrapped < 0.0 { wrapped + TAU } else { wrapped };
+
+        // Adding TAU to a tiny negative value can round up to exactly TAU
+        Self(if wrapped >= TAU { 0.0 } else { wrapped })
+    }
+
+    /// New angle from degrees, normalized into `[0, 2π)`
+    pub fn degrees(degrees: f32) -> Self {
+        Self::radians(degrees.to_radians())
+    }
+
+    /// Angle in radians, within `[0, 2π)`
+    pub fn to_radians(self) -> f32 {
+        self.0
+    }
+
+    /// Cosine of the angle
+    pub fn cos(self) -> f32 {
+        self.0.cos()
+    }
+
+    /// Sine of the angle
+    pub fn sin(self) -> f32 {
+        self.0.sin()
+    }
+}
+
+impl Add for Angle {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::radians(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Angle {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::radians(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Angle {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::radians(-self.0)
+    }
+}
+
 /// Vector of direction and magnitude
 #[derive(Debug, Clone, Copy)]
 pub struct AngleVec {
     /// Angle from +x axis
-    pub direction: f32,
+    pub direction: Angle,
     /// Magnitude of vector
     pub magnitude: f32,
 }
@@ -13,7 +75,7 @@ impl AngleVec {
     /// Convert xy values into angle vector
     pub fn from_xy(x: f32, y: f32) -> Self {
         let magnitude = (x * x + y * y).sqrt();
-        let direction = y.atan2(x);
+        let direction = Angle::radians(y.atan2(x));
 
         Self {
             magnitude,
@@ -50,6 +112,13 @@ pub fn slow(value: &mut f32, deceleration: f32) {
     }
 }
 
+/// Motion state of a ball, so a ball at rest can be skipped until something disturbs it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionState {
+    Moving,
+    Stopped,
+}
+
 /// Ball with position and velocity
 #[derive(Debug, Clone, Copy)]
 pub struct Ball {
@@ -63,6 +132,8 @@ pub struct Ball {
     pub color: Color,
     /// Whether ball is currently colliding
     pub is_colliding: bool,
+    /// Whether the ball is moving or has come to rest
+    pub motion: MotionState,
 }
 
 impl Ball {
@@ -76,20 +147,30 @@ impl Ball {
     pub const BOUNCE_DECELERATION: f32 = 2.0;
     /// Relative magnitude of velocity in slow mode
     pub const SLOW_MAGNITUDE: f32 = 0.2;
+    /// Restitution (bounciness) used when resolving ball-to-ball collisions
+    pub const RESTITUTION: f32 = 0.8;
+    /// Velocity magnitude below which a ball is considered at rest
+    pub const REST_THRESHOLD: f32 = 0.5;
 
     /// New ball with x, y, radius, color, and zero velocity
     pub fn new(x: f32, y: f32, radius: f32, color: Color) -> Self {
         Self {
             point: Point2 { x, y },
             velocity: AngleVec {
-                direction: 0.0,
+                direction: Angle::radians(0.0),
                 magnitude: 0.0,
             },
             radius,
             color,
             is_colliding: false,
+            motion: MotionState::Moving,
         }
     }
+
+    /// Mass of ball, proportional to area
+    pub fn mass(&self) -> f32 {
+        self.radius * self.radius
+    }
 }
 
 pub trait Collides<T> {
@@ -103,3 +184,95 @@ impl Collides<Self> for Ball {
             <= (self.radius + other.radius).powi(2)
     }
 }
+
+/// A straight wall/obstacle, as a line segment between two points
+#[derive(Debug, Clone, Copy)]
+pub struct Wall {
+    /// First endpoint of the segment
+    pub a: Point2<f32>,
+    /// Second endpoint of the segment
+    pub b: Point2<f32>,
+}
+
+impl Wall {
+    /// New wall between two points
+    pub fn new(a: Point2<f32>, b: Point2<f32>) -> Self {
+        Self { a, b }
+    }
+
+    /// Unit normal of the wall segment
+    pub fn normal(&self) -> (f32, f32) {
+        let dx = self.b.x - self.a.x;
+        let dy = self.b.y - self.a.y;
+        let length = (dx * dx + dy * dy).sqrt();
+
+        (-dy / length, dx / length)
+    }
+
+    /// Closest point on the wall segment to a given point
+    pub fn closest_point(&self, point: Point2<f32>) -> Point2<f32> {
+        let abx = self.b.x - self.a.x;
+        let aby = self.b.y - self.a.y;
+        let length_squared = abx * abx + aby * aby;
+
+        let mut t = if length_squared > 0.0 {
+            ((point.x - self.a.x) * abx + (point.y - self.a.y) * aby) / length_squared
+        } else {
+            0.0
+        };
+        clamp(&mut t, 0.0, 1.0);
+
+        Point2 {
+            x: self.a.x + abx * t,
+            y: self.a.y + aby * t,
+        }
+    }
+}
+
+impl Collides<Wall> for Ball {
+    fn collides(&self, other: &Wall) -> bool {
+        let closest = other.closest_point(self.point);
+        let dx = self.point.x - closest.x;
+        let dy = self.point.y - closest.y;
+
+        (dx * dx + dy * dy).sqrt() < self.radius
+    }
+}
+
+/// Target hole for putting mode - the player ball comes to rest inside to score
+#[derive(Debug, Clone, Copy)]
+pub struct Hole {
+    /// Position of hole
+    pub point: Point2<f32>,
+    /// Capture radius of hole
+    pub radius: f32,
+}
+
+impl Hole {
+    /// New hole at a position with a capture radius
+    pub fn new(point: Point2<f32>, radius: f32) -> Self {
+        Self { point, radius }
+    }
+
+    /// Whether a ball at rest inside this hole counts as sunk
+    ///
+    /// The ball must actually fit inside the hole, not just have its center
+    /// within the hole's radius
+    pub fn captures(&self, ball: &Ball) -> bool {
+        if ball.radius > self.radius {
+            return false;
+        }
+
+        let dx = ball.point.x - self.point.x;
+        let dy = ball.point.y - self.point.y;
+
+        (dx * dx + dy * dy).sqrt() < self.radius - ball.radius
+    }
+}
+
+impl Collides<Hole> for Ball {
+    fn collides(&self, other: &Hole) -> bool {
+        (self.point.x - other.point.x).powi(2) + (self.point.y - other.point.y).powi(2)
+            <= (self.radius + other.radius).powi(2)
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::ops::RangeInclusive;
 
@@ -8,10 +9,25 @@ use ggez::input::keyboard::KeyCode;
 use ggez::mint::Point2;
 use ggez::{Context, ContextBuilder, GameResult};
 
-use balls_game::{clamp, slow, AngleVec, Ball, Collides};
+use balls_game::{clamp, slow, Angle, AngleVec, Ball, Collides, Hole, MotionState, Wall};
+use gilrs::{Axis, Button, EventType, Gilrs, GamepadId};
 use rand::Rng;
 
 const BALL_COUNT: RangeInclusive<u32> = 5..=40;
+const WALL_COUNT: RangeInclusive<u32> = 2..=5;
+const WALL_LENGTH: RangeInclusive<f32> = 100.0..=300.0;
+const HOLE_COUNT: RangeInclusive<u32> = 1..=3;
+// Must exceed the largest possible ball radius (90.0) so the player ball can always fit
+const HOLE_RADIUS: RangeInclusive<f32> = 100.0..=150.0;
+/// How fast the virtual gamepad cursor moves per frame at full stick deflection
+const GAMEPAD_CURSOR_SPEED: f32 = 15.0;
+
+/// Source of a ball-grab input, so each player can drag a different ball at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InputSource {
+    Mouse,
+    Gamepad(GamepadId),
+}
 
 fn main() -> GameResult {
     // Create game context
@@ -36,10 +52,29 @@ fn main() -> GameResult {
 struct BallsGame {
     /// All balls in game
     balls: Vec<Ball>,
-    /// Active ball, which is selected
+    /// Walls/obstacles in the level
+    walls: Vec<Wall>,
+    /// Putting mode targets, which the player ball must come to rest inside
+    holes: Vec<Hole>,
+    /// Index of the ball the player is putting, in `balls`
+    player_ball: usize,
+    /// Number of times the player ball has been flung
+    strokes: u32,
+    /// Number of holes the player ball has been sunk in
+    holed: u32,
+    /// Whether the player ball is currently resting inside a hole, to only score it once
+    player_ball_sunk: bool,
+    /// Ball grabbed by each input source, which is selected
     ///
     /// This should be a reference for safety, but that is difficult
-    active_ball: Option<usize>,
+    active_balls: HashMap<InputSource, usize>,
+    /// Gamepad input, polled for connected controller events
+    ///
+    /// `None` if the gamepad backend failed to initialize (e.g. no udev access),
+    /// in which case mouse/keyboard play still works and gamepad input is skipped
+    gilrs: Option<Gilrs>,
+    /// Virtual cursor position per connected gamepad, driven by the left stick
+    gamepad_cursors: HashMap<GamepadId, Point2<f32>>,
 }
 
 impl BallsGame {
@@ -75,9 +110,63 @@ impl BallsGame {
         // Sort so smallest balls are last - Drawn in front
         balls.sort_by(|a, b| b.radius.partial_cmp(&a.radius).unwrap());
 
+        // Scatter a few wall obstacles, skipping any that overlap a spawned ball
+        let mut walls = vec![];
+        'wall: for _ in 0..rng.gen_range(WALL_COUNT) {
+            let x = rng.gen_range(0.0..width);
+            let y = rng.gen_range(0.0..height);
+            let length = rng.gen_range(WALL_LENGTH);
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+
+            let wall = Wall::new(
+                Point2 { x, y },
+                Point2 {
+                    x: x + angle.cos() * length,
+                    y: y + angle.sin() * length,
+                },
+            );
+
+            for ball in &balls {
+                if ball.collides(&wall) {
+                    continue 'wall;
+                }
+            }
+
+            walls.push(wall);
+        }
+
+        // Scatter a few putting holes, skipping any that overlap a spawned ball
+        let mut holes = vec![];
+        'hole: for _ in 0..rng.gen_range(HOLE_COUNT) {
+            let radius = rng.gen_range(HOLE_RADIUS);
+            let hole = Hole::new(
+                Point2 {
+                    x: rng.gen_range(radius..(width - radius)),
+                    y: rng.gen_range(radius..(height - radius)),
+                },
+                radius,
+            );
+
+            for ball in &balls {
+                if ball.collides(&hole) {
+                    continue 'hole;
+                }
+            }
+
+            holes.push(hole);
+        }
+
         BallsGame {
             balls,
-            active_ball: None,
+            walls,
+            holes,
+            player_ball: 0,
+            strokes: 0,
+            holed: 0,
+            player_ball_sunk: false,
+            active_balls: HashMap::new(),
+            gilrs: Gilrs::new().ok(),
+            gamepad_cursors: HashMap::new(),
         }
     }
 }
@@ -96,7 +185,7 @@ impl EventHandler for BallsGame {
 
         // Move active ball to cursor if 'Z' key is pressed
         if ctx.keyboard.is_key_pressed(KeyCode::Z) {
-            if let Some(active) = self.active_ball {
+            if let Some(&active) = self.active_balls.get(&InputSource::Mouse) {
                 let ball = &mut self.balls[active];
                 ball.point.x = cursor.x;
                 ball.point.y = cursor.y;
@@ -106,8 +195,8 @@ impl EventHandler for BallsGame {
         // Move balls with mouse
         if mouse.button_just_pressed(MouseButton::Left) {
             // Change active ball
-            // Default to None
-            self.active_ball = None;
+            // Default to none grabbed
+            self.active_balls.remove(&InputSource::Mouse);
 
             for (i, ball) in self.balls.iter().enumerate() {
                 // Mouse collides with ball
@@ -115,127 +204,281 @@ impl EventHandler for BallsGame {
                     && (ball.point.y - cursor.y).abs() < ball.radius
                 {
                     // Use this ball, and break loop
-                    self.active_ball = Some(i);
+                    self.active_balls.insert(InputSource::Mouse, i);
                     break;
                 }
             }
         } else if mouse.button_just_released(MouseButton::Left) {
             // Apply velocity to active ball, if exists
-            if let Some(active) = self.active_ball {
+            if let Some(active) = self.active_balls.remove(&InputSource::Mouse) {
                 let ball = &mut self.balls[active];
 
                 // Get velocity vector
                 ball.velocity = AngleVec::from_xy(ball.point.x - cursor.x, ball.point.y - cursor.y);
                 // Apply acceleration speed
                 ball.velocity.magnitude *= Ball::ACCELERATION;
+                ball.motion = MotionState::Moving;
+
+                if active == self.player_ball {
+                    self.strokes += 1;
+                }
             }
+        }
+
+        // Size of canvas, needed before the gamepad cursors can be clamped to it
+        let (width, height) = ctx.gfx.drawable_size();
 
-            // Reset active ball
-            self.active_ball = None;
+        // Drain gamepad events, so button presses/releases aren't missed between frames
+        // No-op if the gamepad backend failed to initialize
+        let mut gamepad_events = vec![];
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(event) = gilrs.next_event() {
+                gamepad_events.push((event.id, event.event));
+            }
         }
 
-        // Loop balls
-        for ball in &mut self.balls {
-            // Apply min and max velocity
-            clamp(
-                &mut ball.velocity.magnitude,
-                -Ball::MAX_VELOCITY,
-                Ball::MAX_VELOCITY,
-            );
+        for (id, event) in gamepad_events {
+            let cursor = *self
+                .gamepad_cursors
+                .entry(id)
+                .or_insert(Point2 {
+                    x: width / 2.0,
+                    y: height / 2.0,
+                });
+
+            match event {
+                // Grab the nearest ball under the virtual cursor, same test as the mouse
+                EventType::ButtonPressed(Button::South, _) => {
+                    self.active_balls.remove(&InputSource::Gamepad(id));
+
+                    let distance_squared = |ball: &Ball| {
+                        (ball.point.x - cursor.x).powi(2) + (ball.point.y - cursor.y).powi(2)
+                    };
+                    let nearest = self
+                        .balls
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, ball)| {
+                            (ball.point.x - cursor.x).abs() < ball.radius
+                                && (ball.point.y - cursor.y).abs() < ball.radius
+                        })
+                        .min_by(|(_, a), (_, b)| {
+                            distance_squared(*a).partial_cmp(&distance_squared(*b)).unwrap()
+                        });
+
+                    if let Some((i, _)) = nearest {
+                        self.active_balls.insert(InputSource::Gamepad(id), i);
+                    }
+                }
+                // Fling the grabbed ball using the accumulated stick vector, like a mouse release
+                EventType::ButtonReleased(Button::South, _) => {
+                    if let Some(active) = self.active_balls.remove(&InputSource::Gamepad(id)) {
+                        let ball = &mut self.balls[active];
+
+                        ball.velocity =
+                            AngleVec::from_xy(ball.point.x - cursor.x, ball.point.y - cursor.y);
+                        ball.velocity.magnitude *= Ball::ACCELERATION;
+                        ball.motion = MotionState::Moving;
+
+                        if active == self.player_ball {
+                            self.strokes += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
 
-            // Use slow mode
-            let speed = if ctx.keyboard.is_key_pressed(KeyCode::C) {
-                Ball::SLOW_MAGNITUDE
-            } else {
-                1.0
-            };
+        // Move each connected gamepad's virtual cursor with its left stick
+        // No-op if the gamepad backend failed to initialize
+        if let Some(gilrs) = &self.gilrs {
+            for (id, gamepad) in gilrs.gamepads() {
+                let stick_x = gamepad.value(Axis::LeftStickX);
+                let stick_y = gamepad.value(Axis::LeftStickY);
+
+                let cursor = self.gamepad_cursors.entry(id).or_insert(Point2 {
+                    x: width / 2.0,
+                    y: height / 2.0,
+                });
+                cursor.x += stick_x * GAMEPAD_CURSOR_SPEED;
+                cursor.y -= stick_y * GAMEPAD_CURSOR_SPEED;
+                clamp(&mut cursor.x, 0.0, width);
+                clamp(&mut cursor.y, 0.0, height);
+            }
+        }
 
-            // Apply velocity to ball position
-            let mut velocity = ball.velocity;
-            velocity.magnitude *= speed;
-            let (vx, vy) = velocity.to_xy();
-            ball.point.x += vx;
-            ball.point.y += vy;
-
-            // Decrease velocity slowly for friction
-            slow(&mut ball.velocity.magnitude, Ball::DECELERATION * speed);
-
-            // Size of canvas
-            let (width, height) = ctx.gfx.drawable_size();
-
-            // If ball is out of bounds, flip velocity direction and decrease velocity for bounce force
-            if ball.point.x < ball.radius
-                || ball.point.x > width - ball.radius
-                || ball.point.y < ball.radius
-                || ball.point.y > height - ball.radius
-            {
-                ball.velocity.direction *= -1.0;
-                slow(
+        // Loop balls
+        for ball in &mut self.balls {
+            // Stopped balls skip velocity integration and friction, until grabbed or hit
+            if ball.motion == MotionState::Moving {
+                // Apply min and max velocity
+                clamp(
                     &mut ball.velocity.magnitude,
-                    Ball::BOUNCE_DECELERATION * speed,
+                    -Ball::MAX_VELOCITY,
+                    Ball::MAX_VELOCITY,
                 );
-            }
 
-            // Ball x position is out of bounds
-            // Change direction by a half rotation
-            if ball.point.x < ball.radius {
-                ball.velocity.direction += PI;
-                ball.point.x = ball.radius;
-            } else if ball.point.x > width - ball.radius {
-                ball.velocity.direction += PI;
-                ball.point.x = width - ball.radius;
-            }
+                // Use slow mode
+                let speed = if ctx.keyboard.is_key_pressed(KeyCode::C) {
+                    Ball::SLOW_MAGNITUDE
+                } else {
+                    1.0
+                };
+
+                // Apply velocity to ball position
+                let mut velocity = ball.velocity;
+                velocity.magnitude *= speed;
+                let (vx, vy) = velocity.to_xy();
+                ball.point.x += vx;
+                ball.point.y += vy;
+
+                // Decrease velocity slowly for friction
+                slow(&mut ball.velocity.magnitude, Ball::DECELERATION * speed);
+
+                // Come to rest once friction has slowed the ball enough
+                if ball.velocity.magnitude.abs() < Ball::REST_THRESHOLD {
+                    ball.velocity.magnitude = 0.0;
+                    ball.motion = MotionState::Stopped;
+                }
+
+                // If ball is out of bounds, flip velocity direction and decrease velocity for bounce force
+                if ball.point.x < ball.radius
+                    || ball.point.x > width - ball.radius
+                    || ball.point.y < ball.radius
+                    || ball.point.y > height - ball.radius
+                {
+                    ball.velocity.direction = -ball.velocity.direction;
+                    slow(
+                        &mut ball.velocity.magnitude,
+                        Ball::BOUNCE_DECELERATION * speed,
+                    );
+                }
 
-            // Ball y position is out of bounds
-            if ball.point.y < ball.radius {
-                ball.point.y = ball.radius;
-            } else if ball.point.y > height - ball.radius {
-                ball.point.y = height - ball.radius;
+                // Ball x position is out of bounds
+                // Change direction by a half rotation
+                if ball.point.x < ball.radius || ball.point.x > width - ball.radius {
+                    ball.velocity.direction = ball.velocity.direction + Angle::radians(PI);
+                }
             }
+
+            // Clamp every ball inside the window, even ones at rest that were
+            // shoved out of bounds by a neighbour without being woken up
+            clamp(&mut ball.point.x, ball.radius, width - ball.radius);
+            clamp(&mut ball.point.y, ball.radius, height - ball.radius);
         }
 
-        let mut new_balls = vec![];
+        // Bounce balls off walls, reflecting velocity about the wall's normal
+        for ball in &mut self.balls {
+            for wall in &self.walls {
+                if !ball.collides(wall) {
+                    continue;
+                }
 
-        // Check for collisions with other balls
-        for (i, ball) in self.balls.iter().enumerate() {
-            let mut ball = ball.clone();
-            // Default to not colliding
+                let closest = wall.closest_point(ball.point);
+                let dx = ball.point.x - closest.x;
+                let dy = ball.point.y - closest.y;
+                let distance = (dx * dx + dy * dy).sqrt();
+
+                let (nx, ny) = if distance > 0.0 {
+                    (dx / distance, dy / distance)
+                } else {
+                    wall.normal()
+                };
+
+                // Push the ball out along the normal to remove penetration
+                let penetration = ball.radius - distance;
+                ball.point.x += nx * penetration;
+                ball.point.y += ny * penetration;
+
+                // Reflect velocity about the wall's normal
+                let (vx, vy) = ball.velocity.to_xy();
+                let dot = vx * nx + vy * ny;
+                ball.velocity = AngleVec::from_xy(vx - 2.0 * dot * nx, vy - 2.0 * dot * ny);
+            }
+        }
+
+        // Reset collision flags, recomputed as pairs are checked below
+        for ball in &mut self.balls {
             ball.is_colliding = false;
+        }
 
-            for (j, other) in self.balls.iter().enumerate() {
-                // Ignore collision with self
-                if i == j {
+        // Resolve collisions between ball pairs with an impulse-based solver
+        for i in 0..self.balls.len() {
+            for j in (i + 1)..self.balls.len() {
+                let (left, right) = self.balls.split_at_mut(j);
+                let ball = &mut left[i];
+                let other = &mut right[0];
+
+                if !ball.collides(other) {
                     continue;
                 }
 
-                // Check collision
-                if ball.collides(other) {
-                    ball.is_colliding = true;
+                ball.is_colliding = true;
+                other.is_colliding = true;
 
-                    let x = ball.point.x - other.point.x;
-                    let y = ball.point.y - other.point.y;
+                let dx = other.point.x - ball.point.x;
+                let dy = other.point.y - ball.point.y;
+                let distance = (dx * dx + dy * dy).sqrt();
 
-                    // let sum_magnitude = ball.velocity.magnitude + other.velocity.magnitude;
+                // Balls are exactly on top of each other - separate along an arbitrary normal
+                let (nx, ny) = if distance > 0.0 {
+                    (dx / distance, dy / distance)
+                } else {
+                    (1.0, 0.0)
+                };
 
-                    let radius_ratio = ball.radius / other.radius;
+                let ball_mass = ball.mass();
+                let other_mass = other.mass();
 
-                    let new = AngleVec {
-                        direction: y.atan2(x),
-                        // magnitude: sum_magnitude / 2.0 / radius_ratio,
-                        magnitude: 10.0 / radius_ratio,
-                    };
+                // Positionally separate the balls, split by inverse mass, so they no longer overlap
+                let overlap = ball.radius + other.radius - distance;
+                if overlap > 0.0 {
+                    let inverse_mass_sum = 1.0 / ball_mass + 1.0 / other_mass;
+                    let ball_share = overlap * (1.0 / ball_mass) / inverse_mass_sum;
+                    let other_share = overlap * (1.0 / other_mass) / inverse_mass_sum;
 
-                    ball.velocity = AngleVec {
-                        direction: (new.direction + ball.velocity.direction) / 2.0,
-                        magnitude: (new.magnitude + ball.velocity.magnitude) / 2.0,
-                    }
+                    ball.point.x -= nx * ball_share;
+                    ball.point.y -= ny * ball_share;
+                    other.point.x += nx * other_share;
+                    other.point.y += ny * other_share;
                 }
-            }
 
-            new_balls.push(ball);
+                // Apply a normal impulse so momentum is conserved between the pair
+                let (vx1, vy1) = ball.velocity.to_xy();
+                let (vx2, vy2) = other.velocity.to_xy();
+                let relative_normal_speed = (vx2 - vx1) * nx + (vy2 - vy1) * ny;
+
+                // Balls are already separating - no impulse needed
+                if relative_normal_speed >= 0.0 {
+                    continue;
+                }
+
+                let impulse = -(1.0 + Ball::RESTITUTION) * relative_normal_speed
+                    / (1.0 / ball_mass + 1.0 / other_mass);
+
+                ball.motion = MotionState::Moving;
+                other.motion = MotionState::Moving;
+
+                ball.velocity = AngleVec::from_xy(
+                    vx1 - (impulse / ball_mass) * nx,
+                    vy1 - (impulse / ball_mass) * ny,
+                );
+                other.velocity = AngleVec::from_xy(
+                    vx2 + (impulse / other_mass) * nx,
+                    vy2 + (impulse / other_mass) * ny,
+                );
+            }
         }
 
-        self.balls = new_balls;
+        // Score a hole once the player ball comes to rest inside one
+        let player_ball = &self.balls[self.player_ball];
+        let sunk = player_ball.motion == MotionState::Stopped
+            && self.holes.iter().any(|hole| hole.captures(player_ball));
+
+        if sunk && !self.player_ball_sunk {
+            self.holed += 1;
+        }
+        self.player_ball_sunk = sunk;
 
         Ok(())
     }
@@ -243,8 +486,42 @@ impl EventHandler for BallsGame {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         let mut canvas = graphics::Canvas::from_frame(ctx, graphics::Color::BLACK);
 
+        // Draw walls
+        for wall in &self.walls {
+            let line = graphics::Mesh::new_line(
+                ctx,
+                &[wall.a, wall.b],
+                20.0,
+                Color::from_rgb(120, 120, 120),
+            )?;
+            canvas.draw(&line, graphics::DrawParam::default());
+        }
+
+        // Draw putting holes
+        for hole in &self.holes {
+            let circle = graphics::Mesh::new_circle(
+                ctx,
+                DrawMode::stroke(5.0),
+                hole.point,
+                hole.radius,
+                0.1,
+                Color::WHITE,
+            )?;
+            canvas.draw(&circle, graphics::DrawParam::default());
+        }
+
+        // Draw stroke and holed counts
+        let scoreboard = graphics::Text::new(format!(
+            "Strokes: {}  Holed: {}",
+            self.strokes, self.holed
+        ));
+        canvas.draw(
+            &scoreboard,
+            graphics::DrawParam::default().dest(Point2 { x: 10.0, y: 10.0 }),
+        );
+
         // Draw balls
-        for ball in &self.balls {
+        for (i, ball) in self.balls.iter().enumerate() {
             // Fill circle
             let circle = graphics::Mesh::new_circle(
                 ctx,
@@ -256,6 +533,19 @@ impl EventHandler for BallsGame {
             )?;
             canvas.draw(&circle, graphics::DrawParam::default());
 
+            // Stroke circle around the player ball, so it's distinguishable from the rest
+            if i == self.player_ball {
+                let circle = graphics::Mesh::new_circle(
+                    ctx,
+                    DrawMode::stroke(5.0),
+                    ball.point,
+                    ball.radius,
+                    0.1,
+                    Color::WHITE,
+                )?;
+                canvas.draw(&circle, graphics::DrawParam::default());
+            }
+
             // Draw a cross if ball is colliding
             if ball.is_colliding && ctx.keyboard.is_key_pressed(KeyCode::M) {
                 let Point2 { x, y } = ball.point;
@@ -321,8 +611,8 @@ impl EventHandler for BallsGame {
             }
         }
 
-        // Draw active ball, if exists
-        if let Some(active) = self.active_ball {
+        // Draw each grabbed ball, one per input source
+        for (&source, &active) in &self.active_balls {
             let ball = self.balls[active];
 
             // Stroke circle
@@ -336,8 +626,15 @@ impl EventHandler for BallsGame {
             )?;
             canvas.draw(&circle, graphics::DrawParam::default());
 
-            // Line to cursor
-            let cursor = ctx.mouse.position();
+            // Line to cursor, using the mouse or the grabbing gamepad's virtual cursor
+            let cursor = match source {
+                InputSource::Mouse => ctx.mouse.position(),
+                InputSource::Gamepad(id) => self
+                    .gamepad_cursors
+                    .get(&id)
+                    .copied()
+                    .unwrap_or(ball.point),
+            };
             let velocity_point = Point2 {
                 x: ball.point.x * 2.0 - cursor.x,
                 y: ball.point.y * 2.0 - cursor.y,